@@ -0,0 +1,217 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Launches the ipc agent's json rpc server, and the `stop`/`status`/(on Windows) `service`
+//! subcommands that manage a daemon launched with `--detach`.
+//!
+//! `--detach` re-executes this same binary as `daemon` (without `--detach`) in a new session
+//! with its stdio redirected to `daemon_lifecycle::log_file_path`, rather than forking the
+//! current process - by the time `LaunchDaemon::handle` runs, `cli()`'s tokio runtime is already
+//! up, and forking a live multi-threaded runtime is a well-known way to end up with a child that
+//! only has one of its worker threads. Re-exec sidesteps that entirely.
+
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use clap::{Args, Subcommand};
+
+use crate::cli::commands::daemon_lifecycle::{self, DaemonStatus};
+use crate::cli::commands::OutputFormat;
+use crate::cli::{CommandLineHandler, GlobalArguments};
+use crate::server::{new_evm_keystore_from_config, new_fvm_wallet_from_config, Handlers};
+
+#[cfg(windows)]
+use crate::cli::commands::windows_service;
+
+#[derive(Debug, Subcommand)]
+enum DaemonAction {
+    /// Stops a daemon previously launched with `--detach`.
+    Stop,
+    /// Reports whether a daemon previously launched with `--detach` is running.
+    Status,
+    /// Manages this binary as a Windows service, so it starts automatically instead of needing
+    /// an interactively launched `--detach` process.
+    #[cfg(windows)]
+    Service(windows_service::ServiceArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct LaunchDaemonArgs {
+    #[command(subcommand)]
+    action: Option<DaemonAction>,
+
+    /// Serve the plain http json rpc transport, in addition to whatever `server.with_http` in
+    /// the config file already says. Can only turn it on, not off - use the config file to
+    /// disable a transport.
+    #[arg(long)]
+    with_http: bool,
+    /// Serve the websocket json rpc transport (required for `subscribe`/`unsubscribe`), in
+    /// addition to whatever `server.with_ws` in the config file already says.
+    #[arg(long)]
+    with_ws: bool,
+    /// Launch the daemon in the background instead of running in the foreground, recording its
+    /// pid so it can later be managed with `daemon stop`/`daemon status`.
+    #[arg(long)]
+    detach: bool,
+
+    /// Only meant to be passed by the launch arguments `service install` registers with the
+    /// Service Control Manager - hands control to `windows_service::run_as_service` instead of
+    /// running the plain foreground path directly, so the SCM's startup protocol is actually
+    /// satisfied. Hidden since a user invoking `daemon` interactively has no reason to pass it.
+    #[cfg(windows)]
+    #[arg(long, hide = true)]
+    windows_service: bool,
+}
+
+/// Handler for the `daemon` subcommand.
+pub(crate) struct LaunchDaemon;
+
+#[async_trait]
+impl CommandLineHandler for LaunchDaemon {
+    type Arguments = LaunchDaemonArgs;
+
+    async fn handle(global: &GlobalArguments, arguments: &Self::Arguments) -> Result<()> {
+        match &arguments.action {
+            Some(DaemonAction::Stop) => return stop(global),
+            Some(DaemonAction::Status) => return print_status(global),
+            #[cfg(windows)]
+            Some(DaemonAction::Service(service_args)) => {
+                return windows_service::handle(global, service_args)
+            }
+            None => {}
+        }
+
+        #[cfg(windows)]
+        if arguments.windows_service {
+            return windows_service::run_as_service(global);
+        }
+
+        if arguments.detach {
+            return launch_detached(global, arguments);
+        }
+
+        run_foreground(global, arguments).await
+    }
+}
+
+fn print_status(global: &GlobalArguments) -> Result<()> {
+    let status = daemon_lifecycle::status(&global.repo_path())?;
+    match global.format() {
+        OutputFormat::Human => match status {
+            DaemonStatus::Running { pid } => println!("running (pid {pid})"),
+            DaemonStatus::NotRunning => println!("not running"),
+        },
+        OutputFormat::Json => {
+            let payload = match status {
+                DaemonStatus::Running { pid } => serde_json::json!({ "running": true, "pid": pid }),
+                DaemonStatus::NotRunning => serde_json::json!({ "running": false }),
+            };
+            println!("{payload}");
+        }
+    }
+    Ok(())
+}
+
+fn stop(global: &GlobalArguments) -> Result<()> {
+    daemon_lifecycle::stop(&global.repo_path())?;
+    match global.format() {
+        OutputFormat::Human => println!("daemon stopped"),
+        OutputFormat::Json => println!("{}", serde_json::json!({ "stopped": true })),
+    }
+    Ok(())
+}
+
+pub(crate) async fn run_foreground(global: &GlobalArguments, arguments: &LaunchDaemonArgs) -> Result<()> {
+    let reloadable = Arc::new(global.reloadable_config()?);
+    let config = reloadable.get_config();
+
+    let mut server_config = config.server.clone();
+    server_config.with_http |= arguments.with_http;
+    server_config.with_ws |= arguments.with_ws;
+
+    let fvm_wallet = Arc::new(RwLock::new(new_fvm_wallet_from_config(&config)?));
+    let evm_keystore = Arc::new(RwLock::new(new_evm_keystore_from_config(&config)?));
+
+    let handlers = Arc::new(Handlers::new(reloadable, fvm_wallet, evm_keystore)?);
+
+    crate::server::serve(handlers, &server_config).await
+}
+
+/// Re-executes the current binary as `daemon [--with-http] [--with-ws]` (i.e. without
+/// `--detach`, so the child takes the plain foreground path above), with stdio redirected to
+/// the repo's log file and its own process group, then records its pid.
+fn launch_detached(global: &GlobalArguments, arguments: &LaunchDaemonArgs) -> Result<()> {
+    let repo_path = global.repo_path();
+    if let DaemonStatus::Running { pid } = daemon_lifecycle::status(&repo_path)? {
+        return Err(anyhow!(
+            "daemon already running (pid {pid}); stop it first with `daemon stop`"
+        ));
+    }
+
+    let log_file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(daemon_lifecycle::log_file_path(&repo_path))
+        .with_context(|| format!("opening daemon log file under {}", repo_path.display()))?;
+    let log_file_for_stderr = log_file
+        .try_clone()
+        .context("duplicating daemon log file handle")?;
+
+    let exe = std::env::current_exe()
+        .context("locating the current executable to relaunch as a detached daemon")?;
+
+    let mut cmd = std::process::Command::new(exe);
+    cmd.arg("--repo").arg(repo_path.display().to_string());
+    // Forwarded explicitly (rather than left to the child to re-derive) so a custom
+    // `--config-path` the invoking process was given still applies to the detached child - its
+    // default of `<repo>/config.toml` only matches what we just resolved by coincidence.
+    cmd.arg("--config-path")
+        .arg(global.config_path().display().to_string());
+    cmd.arg("daemon");
+    if arguments.with_http {
+        cmd.arg("--with-http");
+    }
+    if arguments.with_ws {
+        cmd.arg("--with-ws");
+    }
+    cmd.stdin(Stdio::null())
+        .stdout(log_file)
+        .stderr(log_file_for_stderr);
+
+    detach(&mut cmd);
+
+    let child = cmd.spawn().context("spawning detached daemon process")?;
+    let pid = child.id();
+    daemon_lifecycle::write_pid_file(&repo_path, pid)?;
+    match global.format() {
+        OutputFormat::Human => println!("daemon launched in the background (pid {pid})"),
+        OutputFormat::Json => println!("{}", serde_json::json!({ "detached": true, "pid": pid })),
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn detach(cmd: &mut std::process::Command) {
+    use std::os::unix::process::CommandExt;
+
+    // SAFETY: `setsid` is async-signal-safe and is the only thing done between fork and exec
+    // here; it just takes the child out of our process group/session so it isn't killed along
+    // with our controlling terminal.
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(windows)]
+fn detach(cmd: &mut std::process::Command) {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, DETACHED_PROCESS};
+
+    cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | DETACHED_PROCESS);
+}
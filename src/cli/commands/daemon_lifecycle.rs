@@ -0,0 +1,166 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Process lifecycle helpers backing `ipc-agent daemon --detach`, `daemon stop` and
+//! `daemon status`. Kept separate from `daemon.rs` since none of this is specific to what the
+//! daemon actually does once it's running - it's just "is there a process, and how do I manage
+//! it from the PID file it left behind".
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+/// Where the daemon's PID file lives for a given repo path. One daemon per repo path, same as
+/// one config per repo path.
+pub(crate) fn pid_file_path(repo_path: &Path) -> PathBuf {
+    repo_path.join("daemon.pid")
+}
+
+/// Where `--detach` redirects stdout/stderr to, since a detached process has no terminal left
+/// to print them to.
+pub(crate) fn log_file_path(repo_path: &Path) -> PathBuf {
+    repo_path.join("daemon.log")
+}
+
+pub(crate) fn write_pid_file(repo_path: &Path, pid: u32) -> Result<()> {
+    fs::write(pid_file_path(repo_path), pid.to_string())
+        .with_context(|| format!("writing pid file under {}", repo_path.display()))
+}
+
+fn read_pid_file(repo_path: &Path) -> Result<Option<u32>> {
+    let raw = match fs::read_to_string(pid_file_path(repo_path)) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!("reading daemon pid file under {}", repo_path.display())
+            })
+        }
+    };
+    raw.trim()
+        .parse()
+        .map(Some)
+        .with_context(|| format!("pid file under {} is corrupt", repo_path.display()))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum DaemonStatus {
+    Running { pid: u32 },
+    NotRunning,
+}
+
+/// Reports whether the daemon recorded in the repo's PID file is still alive. A missing PID
+/// file, e.g. because the daemon was never launched with `--detach`, is treated as
+/// `NotRunning`; any other failure to read it (permissions, corrupt content) is surfaced as an
+/// error rather than silently reported as `NotRunning`, since that pid could still be live.
+pub(crate) fn status(repo_path: &Path) -> Result<DaemonStatus> {
+    let pid = match read_pid_file(repo_path)? {
+        Some(pid) => pid,
+        None => return Ok(DaemonStatus::NotRunning),
+    };
+
+    if is_running(pid) {
+        Ok(DaemonStatus::Running { pid })
+    } else {
+        Ok(DaemonStatus::NotRunning)
+    }
+}
+
+/// Signals the daemon recorded in the repo's PID file to shut down, then removes the PID file.
+pub(crate) fn stop(repo_path: &Path) -> Result<()> {
+    let pid = read_pid_file(repo_path)?
+        .ok_or_else(|| anyhow!("no daemon pid file found under {}", repo_path.display()))?;
+
+    if !is_running(pid) {
+        return Err(anyhow!("no running daemon found for pid {pid}"));
+    }
+
+    terminate(pid)?;
+
+    fs::remove_file(pid_file_path(repo_path)).ok();
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    // Signal 0 does no actual signalling, it just checks whether we're allowed to (and hence
+    // whether the process exists). EPERM means the process exists but is owned by someone
+    // else, which still counts as running; only ESRCH means it's actually gone.
+    if unsafe { libc::kill(pid as libc::pid_t, 0) == 0 } {
+        true
+    } else {
+        std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<()> {
+    let rc = unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "failed to signal daemon process {pid}: {}",
+            std::io::Error::last_os_error()
+        ))
+    }
+}
+
+#[cfg(windows)]
+fn is_running(pid: u32) -> bool {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION};
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == 0 {
+            false
+        } else {
+            CloseHandle(handle);
+            true
+        }
+    }
+}
+
+#[cfg(windows)]
+fn terminate(pid: u32) -> Result<()> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_TERMINATE, 0, pid);
+        if handle == 0 {
+            return Err(anyhow!("failed to open daemon process {pid} to terminate it"));
+        }
+        let ok = TerminateProcess(handle, 1) != 0;
+        CloseHandle(handle);
+        if ok {
+            Ok(())
+        } else {
+            Err(anyhow!("failed to terminate daemon process {pid}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_with_no_pid_file_is_not_running() {
+        let dir = std::env::temp_dir().join("ipc-agent-daemon-lifecycle-test-no-pid");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(status(&dir).unwrap(), DaemonStatus::NotRunning);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn status_with_stale_pid_is_not_running() {
+        let dir = std::env::temp_dir().join("ipc-agent-daemon-lifecycle-test-stale-pid");
+        fs::create_dir_all(&dir).unwrap();
+        // An implausibly large pid that should never correspond to a live process.
+        write_pid_file(&dir, 999_999).unwrap();
+        assert_eq!(status(&dir).unwrap(), DaemonStatus::NotRunning);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
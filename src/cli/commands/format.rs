@@ -0,0 +1,82 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Output formatting for the `--format` global flag. In `human` mode (the default) commands
+//! keep printing whatever text they always have; in `json` mode a command is expected to
+//! render its own success payload as json itself (see `LaunchDaemon::handle` for the pattern:
+//! check `global.format()`, print a json object instead of text when it's `Json`). What this
+//! module guarantees uniformly, regardless of which command ran, is that a *failure* always
+//! comes out as a single well-formed json object on stderr with a non-zero exit, so tools
+//! wrapping `ipc-agent` never have to scrape an error string.
+
+use anyhow::Error;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+#[clap(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable text, printed however the individual command already prints it.
+    #[default]
+    Human,
+    /// json: errors always come out as `{"error": {...}}` on stderr; success payloads are up
+    /// to each command to render (most already print to stdout, and should switch to printing
+    /// json there instead when this format is selected).
+    Json,
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Human => write!(f, "human"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonError {
+    message: String,
+    /// Each link of the `anyhow` cause chain, outermost first, after the top-level message.
+    context: Vec<String>,
+}
+
+impl From<&Error> for JsonError {
+    fn from(err: &Error) -> Self {
+        let mut chain = err.chain();
+        let message = chain
+            .next()
+            .map(|c| c.to_string())
+            .unwrap_or_else(|| err.to_string());
+        let context = chain.map(|c| c.to_string()).collect();
+        JsonError { message, context }
+    }
+}
+
+/// Renders `err` as the single json object `--format json` promises on failure.
+pub(crate) fn render_error(err: &Error) -> String {
+    json!({ "error": JsonError::from(err) }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_error_flattens_the_cause_chain() {
+        let err = anyhow::anyhow!("root cause")
+            .context("middle")
+            .context("outer");
+        let json_err = JsonError::from(&err);
+        assert_eq!(json_err.message, "outer");
+        assert_eq!(json_err.context, vec!["middle", "root cause"]);
+    }
+
+    #[test]
+    fn render_error_produces_the_documented_shape() {
+        let err = anyhow::anyhow!("boom");
+        let rendered = render_error(&err);
+        let parsed: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(parsed["error"]["message"], "boom");
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! A small http json rpc client shared by every command that talks to a running daemon.
+//!
+//! [`call`] always performs the one-time `ipc_version` handshake (see
+//! `crate::cli::commands::protocol`) before issuing the command's real request, so a client
+//! built against a newer/older protocol, or against a daemon that never registered the method
+//! being called, fails with a precise message instead of whatever error the real request
+//! happens to produce.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use url::Url;
+
+use crate::cli::commands::protocol::check_handshake;
+use crate::cli::GlobalArguments;
+use crate::server::handlers::{IpcVersionResponse, Method};
+
+/// Issues a single json rpc call against `url`, first performing the protocol handshake for
+/// `method`. Takes `global` rather than a bare token so every call site forwards
+/// `global.token()` by construction - a command can't forget to attach it, and a daemon
+/// configured with `[[auth.tokens]]` doesn't quietly lock every command but `ipc_version` out
+/// from under the CLI.
+pub(crate) async fn call<P: Serialize, R: DeserializeOwned>(
+    url: &Url,
+    global: &GlobalArguments,
+    method: &str,
+    params: &P,
+) -> Result<R> {
+    let client = reqwest::Client::new();
+    handshake(&client, url, &method.to_string()).await?;
+    request(&client, url, global.token().as_deref(), method, params).await
+}
+
+/// Fetches `ipc_version` from the daemon at `url` and checks it against what this build
+/// supports and expects to call, per [`check_handshake`].
+async fn handshake(client: &reqwest::Client, url: &Url, method: &Method) -> Result<()> {
+    let version: IpcVersionResponse = request(client, url, None, "ipc_version", &()).await?;
+    check_handshake(&version, method)
+}
+
+async fn request<P: Serialize, R: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &Url,
+    token: Option<&str>,
+    method: &str,
+    params: &P,
+) -> Result<R> {
+    let mut req = client.post(url.clone()).json(&serde_json::json!({
+        "method": method,
+        "params": params,
+    }));
+    if let Some(token) = token {
+        req = req.bearer_auth(token);
+    }
+
+    let response = req
+        .send()
+        .await
+        .with_context(|| format!("calling `{method}` at {url}"))?;
+
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .with_context(|| format!("parsing response to `{method}`"))?;
+
+    if let Some(error) = body.get("error") {
+        return Err(anyhow!("`{method}` failed: {error}"));
+    }
+
+    let result = body
+        .get("result")
+        .cloned()
+        .ok_or_else(|| anyhow!("`{method}` response had neither `result` nor `error`"))?;
+    serde_json::from_value(result)
+        .with_context(|| format!("unexpected response shape for `{method}`"))
+}
@@ -0,0 +1,68 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Protocol version and capability negotiation between this CLI build and the daemon it talks
+//! to, so that a mismatch fails with a clear message instead of an opaque "method not
+//! supported" or a subtly wrong response shape.
+
+use anyhow::{anyhow, Result};
+
+use crate::server::handlers::{IpcVersionResponse, Method};
+
+/// The oldest daemon protocol version this build of the CLI can still talk to.
+pub(crate) const MIN_SUPPORTED_PROTOCOL_VERSION: u32 = 1;
+/// The newest daemon protocol version this build of the CLI knows about.
+pub(crate) const MAX_SUPPORTED_PROTOCOL_VERSION: u32 = 1;
+
+/// Checks a daemon's `ipc_version` response against what this CLI build supports, and that
+/// `method` is actually registered on that daemon. `jsonrpc_client::call` calls this once per
+/// call, right after fetching `ipc_version` and before issuing its real request.
+pub(crate) fn check_handshake(version: &IpcVersionResponse, method: &Method) -> Result<()> {
+    if version.protocol_version < MIN_SUPPORTED_PROTOCOL_VERSION
+        || version.protocol_version > MAX_SUPPORTED_PROTOCOL_VERSION
+    {
+        return Err(anyhow!(
+            "agent speaks protocol {}, this client supports {}..{}",
+            version.protocol_version,
+            MIN_SUPPORTED_PROTOCOL_VERSION,
+            MAX_SUPPORTED_PROTOCOL_VERSION,
+        ));
+    }
+
+    if !version.methods.iter().any(|m| m == method) {
+        return Err(anyhow!(
+            "the connected agent does not implement the `{method}` method"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(protocol_version: u32, methods: &[&str]) -> IpcVersionResponse {
+        IpcVersionResponse {
+            protocol_version,
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn rejects_protocol_outside_supported_range() {
+        let v = version(MAX_SUPPORTED_PROTOCOL_VERSION + 1, &["create_subnet"]);
+        assert!(check_handshake(&v, &"create_subnet".to_string()).is_err());
+    }
+
+    #[test]
+    fn rejects_unregistered_method() {
+        let v = version(MIN_SUPPORTED_PROTOCOL_VERSION, &["create_subnet"]);
+        assert!(check_handshake(&v, &"join_subnet".to_string()).is_err());
+    }
+
+    #[test]
+    fn accepts_supported_version_and_known_method() {
+        let v = version(MIN_SUPPORTED_PROTOCOL_VERSION, &["create_subnet"]);
+        assert!(check_handshake(&v, &"create_subnet".to_string()).is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! `ipc-agent subscribe`: opens the daemon's websocket transport and prints
+//! [`Notification`](crate::server::handlers::Notification)s as they arrive, for whichever topic
+//! was requested. Everything else the CLI does is a single request/response over http; this is
+//! the one command that has to hold a connection open, which is why it goes through
+//! `get_ipc_agent_ws_url` instead of [`super::get_ipc_agent_url`].
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use clap::{Args, Subcommand};
+use futures_util::{SinkExt, StreamExt};
+use ipc_sdk::subnet_id::SubnetID;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::cli::commands::get_ipc_agent_ws_url;
+use crate::cli::{CommandLineHandler, GlobalArguments};
+use crate::server::handlers::{SubscribeParams, SubscribeResponse, SubscriptionTopic};
+
+#[derive(Debug, Subcommand)]
+enum SubscribeTopic {
+    /// Stream newly observed bottom-up checkpoints for a subnet.
+    BottomUpCheckpoints { subnet: String },
+    /// Stream newly observed top-down executions for a subnet.
+    TopDownExecuted { subnet: String },
+}
+
+#[derive(Debug, Args)]
+pub struct SubscribeArgs {
+    #[command(subcommand)]
+    topic: SubscribeTopic,
+    /// The ipc agent's json rpc url, in `ws://` or `wss://` form. Defaults to the daemon
+    /// configured for this repo.
+    #[arg(long, short)]
+    ipc_agent_url: Option<String>,
+}
+
+fn topic(t: &SubscribeTopic) -> Result<SubscriptionTopic> {
+    Ok(match t {
+        SubscribeTopic::BottomUpCheckpoints { subnet } => SubscriptionTopic::BottomUpCheckpoints {
+            subnet: SubnetID::from_str(subnet)?,
+        },
+        SubscribeTopic::TopDownExecuted { subnet } => SubscriptionTopic::TopDownExecuted {
+            subnet: SubnetID::from_str(subnet)?,
+        },
+    })
+}
+
+pub(crate) struct Subscribe;
+
+#[async_trait]
+impl CommandLineHandler for Subscribe {
+    type Arguments = SubscribeArgs;
+
+    async fn handle(global: &GlobalArguments, arguments: &Self::Arguments) -> Result<()> {
+        let url = get_ipc_agent_ws_url(&arguments.ipc_agent_url, global)?;
+        let topic = topic(&arguments.topic)?;
+
+        let mut request = url.as_str().into_client_request()?;
+        if let Some(token) = global.token() {
+            request
+                .headers_mut()
+                .insert(AUTHORIZATION, format!("Bearer {token}").parse()?);
+        }
+
+        let (ws, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .with_context(|| format!("connecting to {url}"))?;
+        let (mut sink, mut stream) = ws.split();
+
+        let request = serde_json::json!({
+            "method": "subscribe",
+            "params": serde_json::to_value(SubscribeParams { topic })?,
+        });
+        sink.send(Message::Text(request.to_string())).await?;
+
+        let reply = stream
+            .next()
+            .await
+            .ok_or_else(|| anyhow!("connection closed before the subscribe request was acknowledged"))??;
+        let reply: serde_json::Value = serde_json::from_str(reply.to_text()?)?;
+        let _: SubscribeResponse = serde_json::from_value(
+            reply
+                .get("result")
+                .cloned()
+                .ok_or_else(|| anyhow!("subscribe failed: {reply}"))?,
+        )?;
+
+        while let Some(message) = stream.next().await {
+            let message = message?;
+            if let Ok(text) = message.to_text() {
+                println!("{text}");
+            }
+        }
+
+        Ok(())
+    }
+}
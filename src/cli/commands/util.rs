@@ -0,0 +1,54 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Miscellaneous commands that don't fit anywhere else.
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+
+use crate::cli::commands::{get_ipc_agent_url, jsonrpc_client, OutputFormat};
+use crate::cli::GlobalArguments;
+use crate::server::handlers::IpcVersionResponse;
+
+#[derive(Debug, Subcommand)]
+enum UtilCommands {
+    /// Reports the connected daemon's protocol version and registered methods. Doubles as a
+    /// quick way to check that a daemon is reachable at all.
+    IpcVersion {
+        /// The ipc agent's json rpc url. Defaults to the daemon configured for this repo.
+        #[arg(long, short)]
+        ipc_agent_url: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct UtilCommandsArgs {
+    #[command(subcommand)]
+    command: UtilCommands,
+}
+
+impl UtilCommandsArgs {
+    pub async fn handle(&self, global: &GlobalArguments) -> Result<()> {
+        match &self.command {
+            UtilCommands::IpcVersion { ipc_agent_url } => {
+                let url = get_ipc_agent_url(ipc_agent_url, global)?;
+                // `call` performs the `ipc_version` handshake itself, against its own response -
+                // redundant here, but that's exactly what keeps this command identical to every
+                // other one instead of a special case.
+                let version: IpcVersionResponse =
+                    jsonrpc_client::call(&url, global, "ipc_version", &()).await?;
+
+                match global.format() {
+                    OutputFormat::Human => {
+                        println!("protocol_version: {}", version.protocol_version);
+                        println!("methods:");
+                        for method in &version.methods {
+                            println!("  - {method}");
+                        }
+                    }
+                    OutputFormat::Json => println!("{}", serde_json::to_string(&version)?),
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Registers (or removes) this binary as a Windows service, so the daemon can be managed through
+//! the Service Control Manager (start at boot, `services.msc`, `sc.exe`) instead of relying on an
+//! interactively-launched `--detach` process, which doesn't survive a reboot.
+//!
+//! Only compiled on Windows; see `daemon::detach` for the non-service `--detach` path shared by
+//! every platform.
+
+use std::ffi::OsString;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::cli::commands::{daemon, Commands, IPCAgentCliCommands};
+use crate::cli::GlobalArguments;
+
+/// Name the service is registered under; also what `sc.exe`/`services.msc` show it as.
+const SERVICE_NAME: &str = "ipc-agent";
+const SERVICE_DISPLAY_NAME: &str = "IPC Agent";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+
+#[derive(Debug, Subcommand)]
+enum ServiceCommand {
+    /// Registers this binary as a Windows service, set to start automatically.
+    Install,
+    /// Unregisters the service installed by `install`. Stops it first if it's running.
+    Uninstall,
+}
+
+#[derive(Debug, Args)]
+pub struct ServiceArgs {
+    #[command(subcommand)]
+    command: ServiceCommand,
+}
+
+pub(crate) fn handle(global: &GlobalArguments, args: &ServiceArgs) -> Result<()> {
+    match args.command {
+        ServiceCommand::Install => install(global),
+        ServiceCommand::Uninstall => uninstall(),
+    }
+}
+
+fn install(global: &GlobalArguments) -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CREATE_SERVICE)
+        .context("connecting to the Windows service manager")?;
+
+    let exe = std::env::current_exe().context("locating the current executable to install")?;
+    let info = ServiceInfo {
+        name: SERVICE_NAME.into(),
+        display_name: SERVICE_DISPLAY_NAME.into(),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: exe,
+        // `--windows-service` is what tells `LaunchDaemon::handle` to hand control to
+        // `run_as_service` below instead of running the plain foreground path directly - the SCM
+        // expects a started service to report `SERVICE_RUNNING` back within its start timeout,
+        // which only the service path does. `--config-path` is forwarded explicitly for the same
+        // reason `daemon::launch_detached` forwards it to its child: left unset, the service
+        // would silently fall back to `<repo>/config.toml` instead of whatever config the
+        // operator running `service install` actually meant.
+        launch_arguments: vec![
+            "--repo".into(),
+            global.repo_path().display().to_string().into(),
+            "--config-path".into(),
+            global.config_path().display().to_string().into(),
+            "daemon".into(),
+            "--windows-service".into(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+
+    manager
+        .create_service(&info, ServiceAccess::empty())
+        .context("registering the ipc-agent Windows service")?;
+
+    println!("installed service `{SERVICE_NAME}`");
+    Ok(())
+}
+
+fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .context("connecting to the Windows service manager")?;
+
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+        )
+        .context("opening the ipc-agent Windows service")?;
+
+    let status = service.query_status().context("querying service status")?;
+    if status.current_state != ServiceState::Stopped {
+        service.stop().context("stopping the ipc-agent service")?;
+    }
+    service
+        .delete()
+        .context("deleting the ipc-agent Windows service")?;
+
+    println!("uninstalled service `{SERVICE_NAME}`");
+    Ok(())
+}
+
+/// Entry point for the `daemon --windows-service` path that `install`'s `launch_arguments`
+/// registers with the SCM. Hands this thread over to the service dispatcher, which blocks here
+/// calling back into [`service_main`] once the SCM has actually started the service - everything
+/// after that point runs on the dispatcher's own thread, not this one.
+pub(crate) fn run_as_service(_global: &GlobalArguments) -> Result<()> {
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        .context("handing control to the Windows service dispatcher")
+}
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// The actual `service_main` the SCM calls back into. `define_windows_service!` requires this
+/// signature exactly (no `Result`, no arguments beyond what the SCM passes), so all it does is
+/// forward to [`run_service`] and report failure the only way it still can at this point.
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(err) = run_service() {
+        eprintln!("ipc-agent service exited with an error: {err:?}");
+    }
+}
+
+fn run_service() -> Result<()> {
+    // `service_main`'s own `arguments` only carries whatever extra params a caller passed to
+    // `StartService` - for a service the SCM starts on its own (the normal case for an
+    // `AutoStart` service at boot) that's empty, not the `--repo ... daemon --windows-service`
+    // we registered as the service's `ImagePath`. Those live in this process's real argv instead,
+    // same as any other invocation of this binary.
+    let cli = IPCAgentCliCommands::parse_from(std::env::args_os());
+
+    let global = cli.global_params;
+    let daemon_args = match cli.command {
+        Some(Commands::Daemon(args)) => args,
+        _ => {
+            return Err(anyhow!(
+                "the Windows service's launch arguments must invoke `daemon --windows-service`"
+            ))
+        }
+    };
+
+    let status_handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        // There's no graceful shutdown signal threaded into `server::serve`, so the most honest
+        // thing to do is exit the process outright rather than claim a clean stop we can't
+        // actually perform.
+        ServiceControl::Stop | ServiceControl::Shutdown => std::process::exit(0),
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })
+    .context("registering the service control handler")?;
+
+    report_status(
+        &status_handle,
+        ServiceState::StartPending,
+        ServiceControlAccept::empty(),
+    )?;
+
+    report_status(
+        &status_handle,
+        ServiceState::Running,
+        ServiceControlAccept::STOP,
+    )?;
+
+    // `service_main` runs synchronously on a dispatcher-owned thread, not inside `cli()`'s tokio
+    // runtime, so the foreground daemon path needs one of its own.
+    let result = tokio::runtime::Runtime::new()
+        .context("starting the tokio runtime for the Windows service")?
+        .block_on(daemon::run_foreground(&global, &daemon_args));
+
+    report_status(
+        &status_handle,
+        ServiceState::Stopped,
+        ServiceControlAccept::empty(),
+    )?;
+
+    result
+}
+
+fn report_status(
+    status_handle: &service_control_handler::ServiceStatusHandle,
+    current_state: ServiceState,
+    controls_accepted: ServiceControlAccept,
+) -> Result<()> {
+    status_handle
+        .set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+        .context("reporting service status to the SCM")
+}
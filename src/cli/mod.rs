@@ -0,0 +1,94 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Shared plumbing for every CLI subcommand: the arguments every subcommand accepts regardless
+//! of which one it is, and the trait each subcommand's handler implements.
+
+pub mod commands;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::cli::commands::OutputFormat;
+use crate::config::{Config, ReloadableConfig};
+
+/// Arguments accepted by every subcommand, declared once here and `#[clap(flatten)]`ed into the
+/// top-level command with `global = true` rather than repeated on every `...CommandsArgs` struct.
+#[derive(Debug, Clone, Args)]
+pub struct GlobalArguments {
+    /// Path to the repo directory holding the agent's config, keystore and daemon state. Falls
+    /// back to [`default_repo_path`] when not set.
+    #[arg(long, short, global = true)]
+    repo: Option<String>,
+
+    /// Path to the agent's config file. Falls back to `<repo>/config.toml` when not set.
+    #[arg(long, global = true)]
+    config_path: Option<String>,
+
+    /// How this invocation should render its output: human-readable text (the default) or a
+    /// single json object, on both success and failure.
+    #[arg(long, value_enum, global = true, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+
+    /// Bearer token to present as `Authorization: Bearer <token>` when a daemon configured with
+    /// `[[auth.tokens]]` requires one. Falls back to `$IPC_AGENT_TOKEN` when not set, so a token
+    /// doesn't have to be typed on every invocation or show up in shell history.
+    #[arg(long, global = true)]
+    token: Option<String>,
+}
+
+impl GlobalArguments {
+    pub fn repo_path(&self) -> PathBuf {
+        PathBuf::from(self.repo.clone().unwrap_or_else(default_repo_path))
+    }
+
+    pub fn config_path(&self) -> PathBuf {
+        match &self.config_path {
+            Some(p) => PathBuf::from(p),
+            None => self.repo_path().join("config.toml"),
+        }
+    }
+
+    /// Loads the agent's config from [`Self::config_path`].
+    pub fn config(&self) -> Result<Config> {
+        Config::from_file(&self.config_path())
+    }
+
+    /// Loads the agent's config wrapped in a [`ReloadableConfig`] that watches
+    /// [`Self::config_path`] for edits.
+    pub fn reloadable_config(&self) -> Result<ReloadableConfig> {
+        ReloadableConfig::new(self.config_path())
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// The bearer token to authenticate with, if any: `--token` takes precedence over
+    /// `$IPC_AGENT_TOKEN`.
+    pub fn token(&self) -> Option<String> {
+        self.token.clone().or_else(|| std::env::var("IPC_AGENT_TOKEN").ok())
+    }
+}
+
+/// Implemented by every subcommand's handler (or, for subcommands with further nested
+/// subcommands, delegated to from their own inherent `handle` method). Kept as a trait rather
+/// than a free function so each handler can be referred to generically, e.g. from `Commands`
+/// dispatch in `cli::commands::cli`.
+#[async_trait::async_trait]
+pub trait CommandLineHandler {
+    /// The subcommand-specific arguments this handler expects, e.g. `LaunchDaemonArgs`.
+    type Arguments;
+
+    async fn handle(global: &GlobalArguments, arguments: &Self::Arguments) -> Result<()>;
+}
+
+/// Where agent state lives when the user doesn't pass `--repo` explicitly: `$HOME/.ipc-agent`,
+/// mirroring the `lotus`/filecoin convention of a dotfile directory under the user's home.
+pub fn default_repo_path() -> String {
+    match home::home_dir() {
+        Some(home) => home.join(".ipc-agent").to_string_lossy().into_owned(),
+        None => ".ipc-agent".to_string(),
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! The agent's on-disk config: where the json rpc server listens, what it's willing to do
+//! without a restart (`ReloadableConfig`), and who's allowed to call it (`auth`).
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use anyhow::{Context, Result};
+use fvm_shared::address::Address;
+use ipc_sdk::subnet_id::SubnetID;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// String constants for every json rpc method name the agent knows about, so that call sites
+/// don't scatter string literals that silently drift out of sync with what's registered in
+/// `Handlers::new`.
+pub mod json_rpc_methods {
+    pub const RELOAD_CONFIG: &str = "reload_config";
+
+    pub const CREATE_SUBNET: &str = "create_subnet";
+    pub const LEAVE_SUBNET: &str = "leave_subnet";
+    pub const KILL_SUBNET: &str = "kill_subnet";
+    pub const JOIN_SUBNET: &str = "join_subnet";
+    pub const RPC_SUBNET: &str = "rpc_subnet";
+
+    pub const FUND: &str = "fund";
+    pub const RELEASE: &str = "release";
+    pub const PROPAGATE: &str = "propagate";
+    pub const SEND_CROSS_MSG: &str = "send_cross_msg";
+    pub const SEND_VALUE: &str = "send_value";
+
+    pub const WALLET_NEW: &str = "wallet_new";
+    pub const WALLET_REMOVE: &str = "wallet_remove";
+    pub const WALLET_IMPORT: &str = "wallet_import";
+    pub const WALLET_EXPORT: &str = "wallet_export";
+    pub const WALLET_BALANCES: &str = "wallet_balances";
+
+    pub const SET_VALIDATOR_NET_ADDR: &str = "set_validator_net_addr";
+    pub const LIST_CHILD_SUBNETS: &str = "list_child_subnets";
+    pub const LIST_BOTTOMUP_CHECKPOINTS: &str = "list_bottomup_checkpoints";
+    pub const LAST_TOPDOWN_EXECUTED: &str = "last_topdown_executed";
+    pub const QUERY_VALIDATOR_SET: &str = "query_validator_set";
+
+    /// Added alongside the `ipc_version` handshake handler.
+    pub const IPC_VERSION: &str = "ipc_version";
+    /// Added alongside the websocket subscription mechanism.
+    pub const SUBSCRIBE: &str = "subscribe";
+    pub const UNSUBSCRIBE: &str = "unsubscribe";
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// Address the json rpc server listens on, for both the http and websocket transports.
+    pub json_rpc_address: SocketAddr,
+    /// Whether the plain http transport is served. Defaults on, since that's all the agent
+    /// spoke before the websocket transport existed.
+    #[serde(default = "default_true")]
+    pub with_http: bool,
+    /// Whether the websocket transport (and therefore subscriptions) is served.
+    #[serde(default)]
+    pub with_ws: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How sensitive a json rpc method is, from least to most dangerous. Granting a token a given
+/// tier authorizes every method at or below that tier, unless the token also carries an explicit
+/// `json_rpc_methods` allow-list, in which case the allow-list is authoritative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensitivity {
+    /// Queries that do not touch key material or subnet state, e.g. listing checkpoints.
+    ReadOnly,
+    /// Methods that read or write wallet/key material but do not move funds on their own.
+    KeyTouching,
+    /// Methods that move funds or otherwise mutate subnet state, e.g. fund, release, kill.
+    FundMoving,
+}
+
+/// One entry of the `[[auth.tokens]]` config section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiTokenConfig {
+    /// The bearer token clients must present in the `Authorization` header.
+    pub token: String,
+    /// The highest [`Sensitivity`] tier this token may call.
+    #[serde(default)]
+    pub sensitivity: Sensitivity,
+    /// If set, restricts this token to exactly these methods regardless of `sensitivity`.
+    #[serde(default)]
+    pub json_rpc_methods: Option<HashSet<String>>,
+}
+
+impl Default for Sensitivity {
+    fn default() -> Self {
+        Sensitivity::ReadOnly
+    }
+}
+
+/// The `[auth]` section: who is allowed to call the json rpc server, and with what scope. An
+/// empty token list means the server is unauthenticated, matching the agent's behavior before
+/// this section existed - operators have to opt in to locking it down.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default, rename = "tokens")]
+    pub tokens: Vec<ApiTokenConfig>,
+}
+
+/// One entry of the `[[subnets]]` config section: how `SubnetManagerPool` reaches and signs for
+/// a subnet the agent manages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubnetConfig {
+    pub id: SubnetID,
+    /// RPC endpoint of a node in this subnet (or, for join/kill, in its parent).
+    #[serde(default)]
+    pub rpc_addr: String,
+    /// Accounts available to sign requests against this subnet, in preference order; used as
+    /// the default `from` address when a manager call doesn't specify one.
+    #[serde(default)]
+    pub accounts: Vec<Address>,
+    /// How often the background poller checks this subnet for new checkpoints/executions.
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Bearer token to present to `rpc_addr`, if the agent on the other end is configured with
+    /// `[[auth.tokens]]` of its own. Separate from the token this agent's own clients
+    /// authenticate with - the two ends can be, and usually are, different deployments.
+    #[serde(default)]
+    pub rpc_token: Option<String>,
+}
+
+fn default_poll_interval_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub subnets: Vec<SubnetConfig>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        toml::from_str(&raw).with_context(|| format!("parsing config file {}", path.display()))
+    }
+}
+
+/// Wraps a [`Config`] so that it can be swapped out at runtime (see `ReloadConfigHandler`)
+/// without restarting the daemon. `generation` is bumped on every reload so callers with their
+/// own caches (e.g. `AuthRegistry`) can tell a stale copy from a fresh one cheaply, without
+/// hashing or deep-comparing the config itself.
+pub struct ReloadableConfig {
+    path: PathBuf,
+    config: RwLock<Config>,
+    generation: AtomicU64,
+}
+
+impl ReloadableConfig {
+    pub fn new(path: PathBuf) -> Result<Self> {
+        let config = Config::from_file(&path)?;
+        Ok(Self {
+            path,
+            config: RwLock::new(config),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns a clone of the currently active config.
+    pub fn get_config(&self) -> Config {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Monotonically increasing counter, bumped by [`Self::reload`]. Two calls returning the
+    /// same value are guaranteed to have seen the same config; that's the only thing callers
+    /// should rely on it for.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Re-reads the config file from disk and swaps it in, bumping `generation` regardless of
+    /// whether the new config is actually different from the old one - callers that care about
+    /// avoiding unnecessary work should compare the config themselves, not infer it from this.
+    pub fn reload(&self) -> Result<()> {
+        let config = Config::from_file(&self.path)?;
+        *self.config.write().unwrap() = config;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Bearer-token authentication and per-method sensitivity classification for the json rpc
+//! server. Every method the agent exposes is either read-only, touches key material, or can
+//! move funds; tokens are granted access up to one of those tiers (or to an explicit allow-list
+//! of methods) so operators can hand out scoped credentials instead of an all-or-nothing secret.
+//!
+//! `Sensitivity` and `ApiTokenConfig` themselves live in `crate::config` since they're part of
+//! the on-disk config shape (`[[auth.tokens]]`); this module only holds the classification of
+//! built-in methods and the registry that checks a token against it.
+
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Result};
+
+pub use crate::config::{ApiTokenConfig, Sensitivity};
+use crate::config::{json_rpc_methods, ReloadableConfig};
+use crate::server::handlers::Method;
+
+/// Returns the [`Sensitivity`] tier of a built-in json rpc method. Methods not listed here are
+/// treated as [`Sensitivity::FundMoving`], i.e. the most restrictive tier, so that forgetting to
+/// classify a newly added handler fails closed instead of open.
+pub(crate) fn method_sensitivity(method: &str) -> Sensitivity {
+    if READ_ONLY.contains(&method) {
+        Sensitivity::ReadOnly
+    } else if KEY_TOUCHING.contains(&method) {
+        Sensitivity::KeyTouching
+    } else {
+        Sensitivity::FundMoving
+    }
+}
+
+const READ_ONLY: &[&str] = &[
+    json_rpc_methods::LIST_CHILD_SUBNETS,
+    json_rpc_methods::LIST_BOTTOMUP_CHECKPOINTS,
+    json_rpc_methods::LAST_TOPDOWN_EXECUTED,
+    json_rpc_methods::QUERY_VALIDATOR_SET,
+    json_rpc_methods::WALLET_BALANCES,
+    // Has to be callable before a client has any business presenting credentials at all.
+    json_rpc_methods::IPC_VERSION,
+    // The push equivalent of `LIST_BOTTOMUP_CHECKPOINTS`/`LAST_TOPDOWN_EXECUTED` above - a token
+    // that can poll that data directly should be able to subscribe to it too.
+    json_rpc_methods::SUBSCRIBE,
+    json_rpc_methods::UNSUBSCRIBE,
+];
+
+const KEY_TOUCHING: &[&str] = &[
+    json_rpc_methods::WALLET_NEW,
+    json_rpc_methods::WALLET_IMPORT,
+    json_rpc_methods::WALLET_REMOVE,
+];
+
+// `WALLET_EXPORT` is deliberately left unclassified so it falls through to the default
+// `FundMoving` tier in `method_sensitivity`: leaking a private key is at least as sensitive as moving funds.
+
+/// Loads and authorizes bearer tokens against the reloadable config, re-reading the token list
+/// whenever the config's generation changes, so that a config reload takes effect without
+/// restarting the daemon.
+pub(crate) struct AuthRegistry {
+    config: Arc<ReloadableConfig>,
+    // Cached against `ReloadableConfig::generation()`, which is bumped on every reload
+    // regardless of whether the new config actually differs from the old one. Using the token
+    // count here instead would miss a token being rotated or re-scoped without the total count
+    // changing, silently serving stale authorization decisions.
+    cache: RwLock<Option<(u64, Vec<ApiTokenConfig>)>>,
+}
+
+impl AuthRegistry {
+    pub fn new(config: Arc<ReloadableConfig>) -> Self {
+        Self {
+            config,
+            cache: RwLock::new(None),
+        }
+    }
+
+    fn tokens(&self) -> Vec<ApiTokenConfig> {
+        let generation = self.config.generation();
+
+        if let Some((gen, tokens)) = self.cache.read().unwrap().as_ref() {
+            if *gen == generation {
+                return tokens.clone();
+            }
+        }
+
+        let tokens = self.config.get_config().auth.tokens;
+        *self.cache.write().unwrap() = Some((generation, tokens.clone()));
+        tokens
+    }
+
+    /// Returns `Ok(())` if `token` is authorized to call `method`, otherwise an error suitable
+    /// for returning straight to the caller.
+    pub fn authorize(&self, token: Option<&str>, method: &Method) -> Result<()> {
+        let tokens = self.tokens();
+        // No `[[auth.tokens]]` configured: the server is unauthenticated (see `AuthConfig`).
+        if tokens.is_empty() {
+            return Ok(());
+        }
+
+        let token = token.ok_or_else(|| anyhow!("missing bearer token"))?;
+
+        let entry = tokens
+            .into_iter()
+            .find(|t| t.token == token)
+            .ok_or_else(|| anyhow!("unrecognized bearer token"))?;
+
+        if let Some(allowed) = &entry.json_rpc_methods {
+            return if allowed.contains(method) {
+                Ok(())
+            } else {
+                Err(anyhow!("token is not authorized for method `{method}`"))
+            };
+        }
+
+        if method_sensitivity(method) <= entry.sensitivity {
+            Ok(())
+        } else {
+            Err(anyhow!("token is not authorized for method `{method}`"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sensitivity_ordering() {
+        assert!(Sensitivity::ReadOnly < Sensitivity::KeyTouching);
+        assert!(Sensitivity::KeyTouching < Sensitivity::FundMoving);
+    }
+
+    #[test]
+    fn subscribe_and_unsubscribe_are_read_only() {
+        assert_eq!(
+            method_sensitivity(json_rpc_methods::SUBSCRIBE),
+            Sensitivity::ReadOnly
+        );
+        assert_eq!(
+            method_sensitivity(json_rpc_methods::UNSUBSCRIBE),
+            Sensitivity::ReadOnly
+        );
+    }
+
+    #[test]
+    fn unclassified_method_defaults_to_fund_moving() {
+        assert_eq!(
+            method_sensitivity("some_future_method"),
+            Sensitivity::FundMoving
+        );
+    }
+
+    #[test]
+    fn rotating_a_token_without_changing_the_count_busts_the_cache() {
+        // Regression test for the cache previously keying on `tokens.len()`: swapping out a
+        // token's value (same count, different generation) must not serve the old entry.
+        let dir = std::env::temp_dir().join("ipc-agent-auth-cache-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.toml");
+
+        std::fs::write(
+            &config_path,
+            r#"
+            [server]
+            json_rpc_address = "127.0.0.1:3030"
+
+            [[auth.tokens]]
+            token = "old-token"
+            sensitivity = "read_only"
+            "#,
+        )
+        .unwrap();
+
+        let reloadable = Arc::new(ReloadableConfig::new(config_path.clone()).unwrap());
+        let registry = AuthRegistry::new(reloadable.clone());
+
+        assert!(registry
+            .authorize(Some("old-token"), &json_rpc_methods::LIST_CHILD_SUBNETS.to_string())
+            .is_ok());
+
+        std::fs::write(
+            &config_path,
+            r#"
+            [server]
+            json_rpc_address = "127.0.0.1:3030"
+
+            [[auth.tokens]]
+            token = "new-token"
+            sensitivity = "read_only"
+            "#,
+        )
+        .unwrap();
+        reloadable.reload().unwrap();
+
+        assert!(registry
+            .authorize(Some("old-token"), &json_rpc_methods::LIST_CHILD_SUBNETS.to_string())
+            .is_err());
+        assert!(registry
+            .authorize(Some("new-token"), &json_rpc_methods::LIST_CHILD_SUBNETS.to_string())
+            .is_ok());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,48 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Subnet manager handlers: everything that needs a live connection to a subnet's parent in
+//! order to act on it (join, kill, and - via `subnet::SubnetManagerPool`'s background pollers -
+//! the checkpoint/top-down subscriptions).
+
+pub mod create;
+pub mod fund;
+pub mod join;
+pub mod kill;
+pub mod leave;
+pub mod list_subnets;
+pub mod propagate;
+pub mod release;
+pub mod send_cross;
+pub mod subnet;
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use fvm_shared::address::Address;
+
+use crate::config::SubnetConfig;
+
+/// Every manager handler needs a `from` address to sign with: either the caller supplied one
+/// explicitly, or we fall back to the first account configured for that subnet.
+pub(crate) fn parse_from(subnet: &SubnetConfig, from: Option<String>) -> Result<Address> {
+    match from {
+        Some(addr) => Address::from_str(&addr).map_err(|e| anyhow!("invalid `from` address: {e}")),
+        None => subnet
+            .accounts
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow!("no `from` address supplied and subnet has no default account configured")),
+    }
+}
+
+/// Rejects requests against a subnet the pool only knows about by id but has no usable
+/// connection details for (e.g. a parent that was referenced but never configured).
+pub(crate) fn check_subnet(subnet: &SubnetConfig) -> Result<()> {
+    if subnet.rpc_addr.is_empty() {
+        return Err(anyhow!(
+            "subnet `{}` has no rpc endpoint configured",
+            subnet.id
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,438 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Owns one connection per configured subnet (`SubnetManagerPool`) and, once
+//! [`SubnetManagerPool::spawn_pollers`] is called, a background task per subnet that watches for
+//! new bottom-up checkpoints and top-down executions and pushes them to
+//! [`SubscriptionRegistry::notify`].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use ethers::types::Address as EthAddress;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use ipc_identity::{PersistentKeyStore, Wallet};
+use ipc_sdk::subnet_id::SubnetID;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::{json_rpc_methods, ReloadableConfig, SubnetConfig};
+use crate::server::handlers::{Notification, SubscriptionRegistry, SubscriptionTopic};
+
+/// The subset of subnet-manager operations the json rpc handlers drive. A real connection talks
+/// to the subnet's own node over its `rpc_addr`; tests can substitute a fake.
+#[async_trait]
+pub trait SubnetManager: Send + Sync {
+    async fn join_subnet(
+        &self,
+        subnet: SubnetID,
+        from: Address,
+        collateral: TokenAmount,
+        validator_net_addr: String,
+        worker_addr: Address,
+    ) -> Result<()>;
+
+    async fn kill_subnet(&self, subnet: SubnetID, from: Address) -> Result<()>;
+
+    /// Returns newly observed bottom-up checkpoints since the last poll, as opaque json - the
+    /// same shape `list_bottomup_checkpoints` would hand a polling client, just pushed instead.
+    async fn poll_bottomup_checkpoints(&self, subnet: &SubnetID) -> Result<Vec<serde_json::Value>>;
+
+    /// Returns the latest top-down execution, as opaque json, if its height advanced since the
+    /// last poll.
+    async fn poll_topdown_executed(&self, subnet: &SubnetID) -> Result<Option<serde_json::Value>>;
+}
+
+/// A connection to a single configured subnet: its config plus the manager used to act on it.
+pub struct SubnetConnection {
+    subnet: SubnetConfig,
+    manager: Arc<dyn SubnetManager>,
+}
+
+impl SubnetConnection {
+    pub fn subnet(&self) -> &SubnetConfig {
+        &self.subnet
+    }
+
+    pub fn manager(&self) -> &Arc<dyn SubnetManager> {
+        &self.manager
+    }
+}
+
+/// Holds one [`SubnetConnection`] per subnet configured in `[[subnets]]`, keyed by subnet id, and
+/// (once [`Self::spawn_pollers`] runs) owns the background tasks that drive push notifications
+/// for subscribed clients.
+pub struct SubnetManagerPool {
+    connections: HashMap<SubnetID, SubnetConnection>,
+}
+
+impl SubnetManagerPool {
+    pub fn new(
+        config: Arc<ReloadableConfig>,
+        fvm_wallet: Arc<RwLock<Wallet>>,
+        evm_keystore: Arc<RwLock<PersistentKeyStore<EthAddress>>>,
+    ) -> Self {
+        let subnets = config.get_config().subnets;
+        let connections = subnets
+            .into_iter()
+            .map(|subnet| {
+                let manager: Arc<dyn SubnetManager> = Arc::new(RpcSubnetManager::new(
+                    subnet.rpc_addr.clone(),
+                    subnet.rpc_token.clone(),
+                    fvm_wallet.clone(),
+                    evm_keystore.clone(),
+                ));
+                (subnet.id.clone(), SubnetConnection { subnet, manager })
+            })
+            .collect();
+
+        Self { connections }
+    }
+
+    pub fn get(&self, subnet: &SubnetID) -> Option<&SubnetConnection> {
+        self.connections.get(subnet)
+    }
+
+    /// Spawns one polling task per configured subnet, each on its own `poll_interval_secs`
+    /// cadence, pushing whatever it observes to `registry` as [`Notification`]s. Fire-and-forget
+    /// by design: the tasks run for as long as the daemon process does.
+    pub fn spawn_pollers(self: &Arc<Self>, registry: Arc<SubscriptionRegistry>) {
+        for subnet_id in self.connections.keys().cloned().collect::<Vec<_>>() {
+            let pool = self.clone();
+            let registry = registry.clone();
+            tokio::spawn(async move { pool.poll_subnet_forever(subnet_id, registry).await });
+        }
+    }
+
+    async fn poll_subnet_forever(&self, subnet_id: SubnetID, registry: Arc<SubscriptionRegistry>) {
+        let Some(conn) = self.get(&subnet_id) else {
+            return;
+        };
+        let interval = Duration::from_secs(conn.subnet.poll_interval_secs.max(1));
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let Some(conn) = self.get(&subnet_id) else {
+                return;
+            };
+
+            match conn.manager().poll_bottomup_checkpoints(&subnet_id).await {
+                Ok(checkpoints) => {
+                    let topic = SubscriptionTopic::BottomUpCheckpoints {
+                        subnet: subnet_id.clone(),
+                    };
+                    for checkpoint in checkpoints {
+                        registry.notify(&topic, checkpoint);
+                    }
+                }
+                Err(err) => {
+                    eprintln!("polling bottom-up checkpoints for subnet {subnet_id} failed: {err}");
+                }
+            }
+
+            match conn.manager().poll_topdown_executed(&subnet_id).await {
+                Ok(Some(payload)) => {
+                    let topic = SubscriptionTopic::TopDownExecuted {
+                        subnet: subnet_id.clone(),
+                    };
+                    registry.notify(&topic, payload);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("polling top-down execution for subnet {subnet_id} failed: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// Talks to a subnet over its configured rpc endpoint, which is itself another ipc-agent daemon
+/// - `rpc_addr` for a child subnet's connection is that subnet's own agent (polled for new
+/// checkpoints/executions), and for a parent's connection (see `parse_from`/`check_subnet`
+/// call sites in `manager::join`/`manager::kill`) it's the parent's agent, which is who actually
+/// has to countersign a join or kill. The wallet/keystore are threaded through for parity with
+/// the other handlers built from this pool; signing stays the responsibility of whichever agent
+/// ultimately submits the subnet-actor message, not this one.
+struct RpcSubnetManager {
+    rpc_addr: String,
+    rpc_token: Option<String>,
+    #[allow(dead_code)]
+    fvm_wallet: Arc<RwLock<Wallet>>,
+    #[allow(dead_code)]
+    evm_keystore: Arc<RwLock<PersistentKeyStore<EthAddress>>>,
+    /// Epoch one past the newest bottom-up checkpoint already delivered to subscribers, so each
+    /// poll only asks for (and notifies on) what's new since the last one.
+    last_checkpoint_epoch: AtomicU64,
+    /// Height of the newest top-down execution already delivered to subscribers.
+    last_topdown_height: AtomicU64,
+}
+
+impl RpcSubnetManager {
+    fn new(
+        rpc_addr: String,
+        rpc_token: Option<String>,
+        fvm_wallet: Arc<RwLock<Wallet>>,
+        evm_keystore: Arc<RwLock<PersistentKeyStore<EthAddress>>>,
+    ) -> Self {
+        Self {
+            rpc_addr,
+            rpc_token,
+            fvm_wallet,
+            evm_keystore,
+            last_checkpoint_epoch: AtomicU64::new(0),
+            last_topdown_height: AtomicU64::new(0),
+        }
+    }
+
+    /// Issues a single json rpc call against this manager's `rpc_addr`, in the same
+    /// request/response shape `crate::cli::commands::jsonrpc_client::call` speaks to our own
+    /// daemon - reasonable, since the other end of this connection is an ipc-agent too. Presents
+    /// `rpc_token` as a bearer token when set, since `JOIN_SUBNET`/`KILL_SUBNET` are
+    /// `FundMoving` - the default, most restrictive tier - so a peer agent with `[[auth.tokens]]`
+    /// configured will reject every join/kill and poll this sends without one.
+    async fn call<P: Serialize, R: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &P,
+    ) -> Result<R> {
+        let client = reqwest::Client::new();
+        let mut request = client
+            .post(&self.rpc_addr)
+            .json(&serde_json::json!({ "method": method, "params": params }));
+        if let Some(token) = &self.rpc_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("calling `{method}` at {}", self.rpc_addr))?;
+
+        let body: Value = response
+            .json()
+            .await
+            .with_context(|| format!("parsing response to `{method}` from {}", self.rpc_addr))?;
+
+        if let Some(error) = body.get("error") {
+            return Err(anyhow!("`{method}` at {} failed: {error}", self.rpc_addr));
+        }
+
+        let result = body
+            .get("result")
+            .cloned()
+            .ok_or_else(|| anyhow!("`{method}` response had neither `result` nor `error`"))?;
+        serde_json::from_value(result)
+            .with_context(|| format!("unexpected response shape for `{method}`"))
+    }
+}
+
+#[derive(Serialize)]
+struct JoinSubnetRpcParams {
+    subnet: String,
+    from: String,
+    collateral: String,
+    validator_net_addr: String,
+    worker_addr: String,
+}
+
+#[derive(Serialize)]
+struct KillSubnetRpcParams {
+    subnet: String,
+    from: String,
+}
+
+#[derive(Serialize)]
+struct ListBottomUpCheckpointsRpcParams {
+    subnet: String,
+    /// Only checkpoints at or after this epoch are of interest; everything older has already
+    /// been delivered to subscribers.
+    from_epoch: u64,
+}
+
+#[derive(Serialize)]
+struct LastTopDownExecutedRpcParams {
+    subnet: String,
+}
+
+/// The highest `epoch` field found across `checkpoints`, or `None` if none carry one.
+fn newest_epoch(checkpoints: &[Value]) -> Option<u64> {
+    checkpoints
+        .iter()
+        .filter_map(|c| c.get("epoch").and_then(Value::as_u64))
+        .max()
+}
+
+#[async_trait]
+impl SubnetManager for RpcSubnetManager {
+    async fn join_subnet(
+        &self,
+        subnet: SubnetID,
+        from: Address,
+        collateral: TokenAmount,
+        validator_net_addr: String,
+        worker_addr: Address,
+    ) -> Result<()> {
+        self.call(
+            json_rpc_methods::JOIN_SUBNET,
+            &JoinSubnetRpcParams {
+                subnet: subnet.to_string(),
+                from: from.to_string(),
+                collateral: collateral.atto().to_string(),
+                validator_net_addr,
+                worker_addr: worker_addr.to_string(),
+            },
+        )
+        .await
+    }
+
+    async fn kill_subnet(&self, subnet: SubnetID, from: Address) -> Result<()> {
+        self.call(
+            json_rpc_methods::KILL_SUBNET,
+            &KillSubnetRpcParams {
+                subnet: subnet.to_string(),
+                from: from.to_string(),
+            },
+        )
+        .await
+    }
+
+    async fn poll_bottomup_checkpoints(&self, subnet: &SubnetID) -> Result<Vec<Value>> {
+        let from_epoch = self.last_checkpoint_epoch.load(Ordering::SeqCst);
+        let checkpoints: Vec<Value> = self
+            .call(
+                json_rpc_methods::LIST_BOTTOMUP_CHECKPOINTS,
+                &ListBottomUpCheckpointsRpcParams {
+                    subnet: subnet.to_string(),
+                    from_epoch,
+                },
+            )
+            .await?;
+
+        if let Some(newest) = newest_epoch(&checkpoints) {
+            self.last_checkpoint_epoch
+                .store(newest + 1, Ordering::SeqCst);
+        }
+
+        Ok(checkpoints)
+    }
+
+    async fn poll_topdown_executed(&self, subnet: &SubnetID) -> Result<Option<Value>> {
+        let executed: Option<Value> = self
+            .call(
+                json_rpc_methods::LAST_TOPDOWN_EXECUTED,
+                &LastTopDownExecutedRpcParams {
+                    subnet: subnet.to_string(),
+                },
+            )
+            .await?;
+
+        let Some(executed) = executed else {
+            return Ok(None);
+        };
+        let Some(height) = executed.get("height").and_then(Value::as_u64) else {
+            return Ok(None);
+        };
+
+        let last = self.last_topdown_height.load(Ordering::SeqCst);
+        if height <= last {
+            return Ok(None);
+        }
+        self.last_topdown_height.store(height, Ordering::SeqCst);
+        Ok(Some(executed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CountingManager {
+        calls: AtomicU64,
+    }
+
+    #[async_trait]
+    impl SubnetManager for CountingManager {
+        async fn join_subnet(
+            &self,
+            _subnet: SubnetID,
+            _from: Address,
+            _collateral: TokenAmount,
+            _validator_net_addr: String,
+            _worker_addr: Address,
+        ) -> Result<()> {
+            unimplemented!()
+        }
+
+        async fn kill_subnet(&self, _subnet: SubnetID, _from: Address) -> Result<()> {
+            unimplemented!()
+        }
+
+        async fn poll_bottomup_checkpoints(
+            &self,
+            _subnet: &SubnetID,
+        ) -> Result<Vec<serde_json::Value>> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![serde_json::json!({ "call": n })])
+        }
+
+        async fn poll_topdown_executed(&self, _subnet: &SubnetID) -> Result<Option<serde_json::Value>> {
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn poller_notifies_subscribers_of_new_checkpoints() {
+        let subnet_id = SubnetID::new_root(0);
+        let subnet = SubnetConfig {
+            id: subnet_id.clone(),
+            rpc_addr: "http://localhost:1234".to_string(),
+            accounts: vec![],
+            poll_interval_secs: 1,
+            rpc_token: None,
+        };
+        let manager: Arc<dyn SubnetManager> = Arc::new(CountingManager {
+            calls: AtomicU64::new(0),
+        });
+        let mut connections = HashMap::new();
+        connections.insert(subnet_id.clone(), SubnetConnection { subnet, manager });
+        let pool = Arc::new(SubnetManagerPool { connections });
+
+        let registry = Arc::new(SubscriptionRegistry::new());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        registry.subscribe(
+            SubscriptionTopic::BottomUpCheckpoints {
+                subnet: subnet_id.clone(),
+            },
+            tx,
+        );
+
+        pool.spawn_pollers(registry);
+
+        let notification: Notification =
+            tokio::time::timeout(Duration::from_secs(5), rx.recv())
+                .await
+                .expect("poller should have notified within the timeout")
+                .expect("channel should still be open");
+        assert_eq!(notification.payload["call"], 0);
+    }
+
+    #[test]
+    fn newest_epoch_ignores_entries_without_one() {
+        let checkpoints = vec![
+            serde_json::json!({ "epoch": 3 }),
+            serde_json::json!({ "epoch": 7 }),
+            serde_json::json!({ "no_epoch_field": true }),
+        ];
+        assert_eq!(newest_epoch(&checkpoints), Some(7));
+    }
+
+    #[test]
+    fn newest_epoch_of_empty_slice_is_none() {
+        assert_eq!(newest_epoch(&[]), None);
+    }
+}
@@ -45,17 +45,36 @@ use self::wallet::export::WalletExportHandler;
 use self::wallet::import::WalletImportHandler;
 use self::wallet::remove::WalletRemoveHandler;
 
+mod auth;
 mod config;
 mod manager;
+mod subscription;
 mod validator;
+mod version;
 pub mod wallet;
 
+pub use auth::{ApiTokenConfig, Sensitivity};
+use auth::AuthRegistry;
+pub use subscription::{
+    Notification, SubscribeParams, SubscribeResponse, SubscriptionId, SubscriptionRegistry,
+    SubscriptionTopic, UnsubscribeParams,
+};
+use version::IpcVersionHandler;
+pub use version::{IpcVersionResponse, PROTOCOL_VERSION};
+
 pub type Method = String;
 /// We only support up to 9 decimal digits for transaction
 const FIL_AMOUNT_NANO_DIGITS: u32 = 9;
 /// The collection of all json rpc handlers
 pub struct Handlers {
     handlers: HashMap<Method, Box<dyn HandlerWrapper>>,
+    auth: AuthRegistry,
+    /// Shared across every websocket connection so a subnet's background poller can look up
+    /// who to notify without knowing which connection a subscription came from. `subscribe`/
+    /// `unsubscribe` are handled by the websocket transport directly (see `crate::server`)
+    /// rather than through `Handlers::handle`, since registering a subscription needs the
+    /// calling connection's own outbound sender, which `handle` has no access to.
+    subscriptions: Arc<SubscriptionRegistry>,
 }
 
 /// A util trait to avoid Box<dyn> and associated type mess in Handlers struct
@@ -77,9 +96,11 @@ impl Handlers {
     /// We test the handlers separately and individually instead of from the handlers.
     /// Convenient method for json rpc to test routing.
     #[cfg(test)]
-    pub fn empty_handlers() -> Self {
+    pub fn empty_handlers(config: Arc<ReloadableConfig>) -> Self {
         Self {
             handlers: HashMap::new(),
+            auth: AuthRegistry::new(config),
+            subscriptions: Arc::new(SubscriptionRegistry::new()),
         }
     }
 
@@ -89,6 +110,7 @@ impl Handlers {
         evm_keystore: Arc<RwLock<PersistentKeyStore<ethers::types::Address>>>,
     ) -> Result<Self> {
         let mut handlers = HashMap::new();
+        let auth = AuthRegistry::new(config.clone());
 
         let h: Box<dyn HandlerWrapper> = Box::new(ReloadConfigHandler::new(config.clone()));
         handlers.insert(String::from(json_rpc_methods::RELOAD_CONFIG), h);
@@ -99,6 +121,8 @@ impl Handlers {
             fvm_wallet.clone(),
             evm_keystore.clone(),
         ));
+        let subscriptions = Arc::new(SubscriptionRegistry::new());
+        pool.spawn_pollers(subscriptions.clone());
         let h: Box<dyn HandlerWrapper> = Box::new(CreateSubnetHandler::new(pool.clone()));
         handlers.insert(String::from(json_rpc_methods::CREATE_SUBNET), h);
 
@@ -147,14 +171,14 @@ impl Handlers {
         ));
         handlers.insert(String::from(json_rpc_methods::WALLET_IMPORT), h);
 
-        let _h: Box<dyn HandlerWrapper> = Box::new(WalletExportHandler::new(
+        // On a daemon configured with `[[auth.tokens]]`, callers must present a bearer token
+        // authorized for the `FundMoving` sensitivity tier (see `auth`) to export a wallet key;
+        // with no tokens configured the server is unauthenticated, this method included.
+        let h: Box<dyn HandlerWrapper> = Box::new(WalletExportHandler::new(
             fvm_wallet.clone(),
             evm_keystore.clone(),
         ));
-        // FIXME: For security reasons currently not exposing the ability to export wallet
-        // remotely through the RPC API, only directly through the CLI.
-        // We can consider re-enabling once we have RPC authentication in the agent.
-        // handlers.insert(String::from(json_rpc_methods::WALLET_EXPORT), h);
+        handlers.insert(String::from(json_rpc_methods::WALLET_EXPORT), h);
 
         let h: Box<dyn HandlerWrapper> = Box::new(WalletBalancesHandler::new(
             pool.clone(),
@@ -180,10 +204,55 @@ impl Handlers {
         let h: Box<dyn HandlerWrapper> = Box::new(QueryValidatorSetHandler::new(pool));
         handlers.insert(String::from(json_rpc_methods::QUERY_VALIDATOR_SET), h);
 
-        Ok(Self { handlers })
+        // `ipc_version` reports every method registered above, so it must be built last; its
+        // own name is added separately since it isn't in `handlers` until the line after this.
+        let mut methods: Vec<Method> = handlers.keys().cloned().collect();
+        methods.push(String::from(json_rpc_methods::IPC_VERSION));
+        let h: Box<dyn HandlerWrapper> = Box::new(IpcVersionHandler::new(methods));
+        handlers.insert(String::from(json_rpc_methods::IPC_VERSION), h);
+
+        Ok(Self {
+            handlers,
+            auth,
+            subscriptions,
+        })
+    }
+
+    /// Shared with the websocket transport so it can register/remove subscriptions against the
+    /// same registry the background pollers in `SubnetManagerPool` notify through.
+    pub fn subscriptions(&self) -> Arc<SubscriptionRegistry> {
+        self.subscriptions.clone()
     }
 
-    pub async fn handle(&self, method: Method, params: Value) -> Result<Value> {
+    /// Returns `Ok(())` if `token` is authorized to call `method`. Exposed so the websocket
+    /// transport can gate `subscribe`/`unsubscribe` the same way this method gates everything
+    /// else, even though those two don't go through `handle` itself (see `subscriptions`).
+    pub fn authorize(&self, token: Option<&str>, method: &Method) -> Result<()> {
+        self.auth.authorize(token, method)
+    }
+
+    /// Dispatches `method` to its registered handler, first checking that `token` is authorized
+    /// to call it. `token` should be the bearer token extracted from the `Authorization` header
+    /// by the json rpc front-end, or `None` if the caller sent no such header.
+    ///
+    /// `subscribe`/`unsubscribe` are not handled here: registering a subscription needs the
+    /// calling connection's own outbound sender so the background poller has somewhere to push
+    /// [`Notification`]s, and `handle` has no notion of "the calling connection". Only the
+    /// websocket transport exposes those two methods, using [`Self::subscriptions`] and
+    /// [`Self::authorize`] directly; the HTTP front-end never reaches them.
+    ///
+    /// `ipc_version` is exempt from the token check: a client has to be able to ask what a
+    /// daemon speaks before it has any business presenting credentials for it.
+    pub async fn handle(
+        &self,
+        token: Option<&str>,
+        method: Method,
+        params: Value,
+    ) -> Result<Value> {
+        if method != json_rpc_methods::IPC_VERSION {
+            self.auth.authorize(token, &method)?;
+        }
+
         if let Some(wrapper) = self.handlers.get(&method) {
             wrapper.handle(params).await
         } else {
@@ -0,0 +1,188 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! Subscription registry for the websocket json rpc transport.
+//!
+//! Plain request/response handlers return a single [`serde_json::Value`] and are done. A
+//! subscription is different: the caller gets an id back immediately, and then zero or more
+//! [`Notification`] frames arrive later, out of band, as a subnet's background poller (see
+//! `manager::subnet::SubnetManagerPool::spawn_pollers`) observes new checkpoints or top-down
+//! executions. Each subscription owns the sending half of the channel that feeds its
+//! connection's outbound loop, so `notify` can push directly to exactly the right sockets
+//! without the poller needing to know anything about websockets.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use ipc_sdk::subnet_id::SubnetID;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Identifies a single subscription for the lifetime of the websocket connection that created
+/// it. Opaque to clients; handed back from `subscribe` and expected in `unsubscribe`.
+pub type SubscriptionId = u64;
+
+/// The event streams a client can subscribe to. Each variant is scoped to a single subnet so
+/// that a client only receives the events it asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "topic", rename_all = "snake_case")]
+pub enum SubscriptionTopic {
+    /// New bottom-up checkpoints (the data `ListBottomUpCheckpointsHandler` serves), observed
+    /// by the subnet's background poller in `SubnetManagerPool`.
+    BottomUpCheckpoints { subnet: SubnetID },
+    /// New top-down executions (the data `LastTopDownExecHandler` serves), observed by the
+    /// subnet's background poller in `SubnetManagerPool`.
+    TopDownExecuted { subnet: SubnetID },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeParams {
+    #[serde(flatten)]
+    pub topic: SubscriptionTopic,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscribeResponse {
+    pub subscription: SubscriptionId,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnsubscribeParams {
+    pub subscription: SubscriptionId,
+}
+
+/// A push frame delivered to a subscribed client, as opposed to the plain json rpc response
+/// frames the same websocket also carries for request/response calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub subscription: SubscriptionId,
+    pub payload: Value,
+}
+
+struct Subscription {
+    topic: SubscriptionTopic,
+    sender: UnboundedSender<Notification>,
+}
+
+/// Tracks live subscriptions and pushes notifications to them. One instance is shared by the
+/// whole json rpc server; each websocket connection registers its own subscriptions, against
+/// its own outbound channel, as it opens, and they're dropped when the connection closes (a
+/// dropped connection's `UnboundedSender` becomes disconnected, which `notify` treats as reason
+/// enough to prune the subscription instead of waiting for an explicit `unsubscribe`).
+#[derive(Default)]
+pub struct SubscriptionRegistry {
+    next_id: AtomicU64,
+    subscriptions: RwLock<HashMap<SubscriptionId, Subscription>>,
+}
+
+impl SubscriptionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new subscription whose notifications are pushed through `sender`, and
+    /// returns the id the client should use to unsubscribe.
+    pub fn subscribe(&self, topic: SubscriptionTopic, sender: UnboundedSender<Notification>) -> SubscriptionId {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        self.subscriptions
+            .write()
+            .unwrap()
+            .insert(id, Subscription { topic, sender });
+        id
+    }
+
+    /// Removes a subscription. Returns `false` if it was already gone.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.subscriptions.write().unwrap().remove(&id).is_some()
+    }
+
+    /// Pushes `payload` to every subscription currently registered against `topic`. Called by a
+    /// subnet's background poller each time it observes a new entry. Subscriptions whose
+    /// connection has gone away (send fails) are pruned as a side effect.
+    pub fn notify(&self, topic: &SubscriptionTopic, payload: Value) {
+        let mut dead = Vec::new();
+
+        {
+            let subs = self.subscriptions.read().unwrap();
+            for (id, sub) in subs.iter().filter(|(_, s)| &s.topic == topic) {
+                let notification = Notification {
+                    subscription: *id,
+                    payload: payload.clone(),
+                };
+                if sub.sender.send(notification).is_err() {
+                    dead.push(*id);
+                }
+            }
+        }
+
+        if !dead.is_empty() {
+            let mut subs = self.subscriptions.write().unwrap();
+            for id in dead {
+                subs.remove(&id);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn topic_of(&self, id: SubscriptionId) -> Option<SubscriptionTopic> {
+        self.subscriptions
+            .read()
+            .unwrap()
+            .get(&id)
+            .map(|s| s.topic.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnet() -> SubnetID {
+        SubnetID::new_root(0)
+    }
+
+    #[test]
+    fn subscribe_and_unsubscribe_roundtrip() {
+        let registry = SubscriptionRegistry::new();
+        let topic = SubscriptionTopic::BottomUpCheckpoints { subnet: subnet() };
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let id = registry.subscribe(topic.clone(), tx);
+        assert_eq!(registry.topic_of(id), Some(topic));
+
+        assert!(registry.unsubscribe(id));
+        assert_eq!(registry.topic_of(id), None);
+        assert!(!registry.unsubscribe(id));
+    }
+
+    #[test]
+    fn notify_delivers_only_to_matching_topic() {
+        let registry = SubscriptionRegistry::new();
+        let bottom_up = SubscriptionTopic::BottomUpCheckpoints { subnet: subnet() };
+        let top_down = SubscriptionTopic::TopDownExecuted { subnet: subnet() };
+
+        let (tx_bu, mut rx_bu) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_td, mut rx_td) = tokio::sync::mpsc::unbounded_channel();
+        registry.subscribe(bottom_up.clone(), tx_bu);
+        registry.subscribe(top_down.clone(), tx_td);
+
+        registry.notify(&bottom_up, serde_json::json!({"epoch": 1}));
+
+        assert!(rx_bu.try_recv().is_ok());
+        assert!(rx_td.try_recv().is_err());
+    }
+
+    #[test]
+    fn notify_prunes_subscriptions_whose_connection_dropped() {
+        let registry = SubscriptionRegistry::new();
+        let topic = SubscriptionTopic::BottomUpCheckpoints { subnet: subnet() };
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let id = registry.subscribe(topic.clone(), tx);
+
+        drop(rx);
+        registry.notify(&topic, serde_json::json!({}));
+
+        assert_eq!(registry.topic_of(id), None);
+    }
+}
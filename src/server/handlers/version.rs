@@ -0,0 +1,46 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! The `ipc_version` handler: a built-in method, always registered and never gated behind
+//! auth, that lets a client discover what it's talking to before it relies on anything else.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::server::handlers::Method;
+use crate::server::JsonRPCRequestHandler;
+
+/// Bumped whenever a breaking change is made to the json rpc wire protocol (new required
+/// params, changed response shape, a method removed). Additive changes, like registering a new
+/// method, do not require a bump: clients should discover those through `methods` instead.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IpcVersionResponse {
+    /// The protocol version this daemon speaks.
+    pub protocol_version: u32,
+    /// Every method name currently registered in this daemon's `Handlers`.
+    pub methods: Vec<Method>,
+}
+
+pub(crate) struct IpcVersionHandler {
+    methods: Vec<Method>,
+}
+
+impl IpcVersionHandler {
+    pub(crate) fn new(methods: Vec<Method>) -> Self {
+        Self { methods }
+    }
+}
+
+#[async_trait]
+impl JsonRPCRequestHandler for IpcVersionHandler {
+    type Request = ();
+    type Response = IpcVersionResponse;
+
+    async fn handle(&self, _request: Self::Request) -> anyhow::Result<Self::Response> {
+        Ok(IpcVersionResponse {
+            protocol_version: PROTOCOL_VERSION,
+            methods: self.methods.clone(),
+        })
+    }
+}
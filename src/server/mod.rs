@@ -0,0 +1,221 @@
+// Copyright 2022-2023 Protocol Labs
+// SPDX-License-Identifier: MIT
+//! The json rpc server: the `JsonRPCRequestHandler` trait every handler implements, and the
+//! http front-end that extracts the `Authorization` header and dispatches into `Handlers`.
+
+pub mod handlers;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json};
+use axum::routing::post;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub use handlers::{
+    new_evm_keystore_from_config, new_evm_keystore_from_path, new_fvm_wallet_from_config,
+    new_keystore_from_path, Handlers,
+};
+use handlers::{SubscribeParams, SubscribeResponse, SubscriptionId, UnsubscribeParams};
+
+use crate::config::{json_rpc_methods, ServerConfig};
+
+/// Implemented by every json rpc method handler. `HandlerWrapper` in `handlers` type-erases
+/// this down to raw `serde_json::Value` in and out so `Handlers` can hold a homogeneous map of
+/// them.
+#[async_trait]
+pub trait JsonRPCRequestHandler {
+    type Request;
+    type Response;
+
+    async fn handle(&self, request: Self::Request) -> Result<Self::Response>;
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonRpcRequest {
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRpcResponse {
+    result: Value,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRpcErrorResponse {
+    error: String,
+}
+
+struct ServerState {
+    handlers: Arc<Handlers>,
+}
+
+/// Pulls the bearer token out of an `Authorization: Bearer <token>` header, if present. Any
+/// other scheme, or a missing header entirely, is treated as "no token" and left to
+/// `AuthRegistry::authorize` to reject for the methods that require one.
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::to_owned)
+}
+
+async fn handle_http(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Json(req): Json<JsonRpcRequest>,
+) -> impl IntoResponse {
+    let token = bearer_token(&headers);
+
+    match state
+        .handlers
+        .handle(token.as_deref(), req.method, req.params)
+        .await
+    {
+        Ok(result) => (StatusCode::OK, Json(JsonRpcResponse { result })).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(JsonRpcErrorResponse {
+                error: format!("{err:?}"),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+async fn handle_ws_upgrade(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let token = bearer_token(&headers);
+    ws.on_upgrade(move |socket| handle_ws(socket, state, token))
+}
+
+/// Drives a single websocket connection: every inbound frame is a json rpc request (the same
+/// method-call shape the http transport accepts, plus `subscribe`/`unsubscribe`, which only this
+/// transport serves), and every
+/// [`handlers::Notification`] raised against this connection's own subscriptions is forwarded
+/// out as soon as it arrives - that's the one thing the http transport structurally can't do.
+async fn handle_ws(socket: WebSocket, state: Arc<ServerState>, token: Option<String>) {
+    use futures_util::{SinkExt, StreamExt};
+
+    let (mut sink, mut stream) = socket.split();
+    let (notify_tx, mut notify_rx) = tokio::sync::mpsc::unbounded_channel();
+    // Subscriptions this connection itself created, so `unsubscribe` can be scoped to them
+    // instead of the registry's global id space - otherwise any `ReadOnly`-tier client could
+    // guess another connection's (small, sequential) subscription id and unsubscribe it.
+    let mut owned_subscriptions = HashSet::new();
+
+    loop {
+        tokio::select! {
+            notification = notify_rx.recv() => {
+                let Some(notification) = notification else { break };
+                let Ok(text) = serde_json::to_string(&notification) else { continue };
+                if sink.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            frame = stream.next() => {
+                let Some(frame) = frame else { break };
+                let Ok(Message::Text(text)) = frame else { continue };
+                let response = handle_ws_request(
+                    &state,
+                    token.as_deref(),
+                    &text,
+                    &notify_tx,
+                    &mut owned_subscriptions,
+                )
+                .await;
+                if sink.send(Message::Text(response)).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // The registry would eventually prune these itself (`notify` drops subscriptions whose send
+    // fails), but only the next time something is published on their topic, which might be
+    // never - clean up eagerly instead of leaking them for the rest of the daemon's lifetime.
+    for id in owned_subscriptions {
+        state.handlers.subscriptions().unsubscribe(id);
+    }
+}
+
+async fn handle_ws_request(
+    state: &ServerState,
+    token: Option<&str>,
+    text: &str,
+    notify_tx: &tokio::sync::mpsc::UnboundedSender<handlers::Notification>,
+    owned_subscriptions: &mut HashSet<SubscriptionId>,
+) -> String {
+    let render = |result: Result<Value>| match result {
+        Ok(result) => serde_json::to_string(&JsonRpcResponse { result }).unwrap_or_default(),
+        Err(err) => serde_json::to_string(&JsonRpcErrorResponse {
+            error: format!("{err:?}"),
+        })
+        .unwrap_or_default(),
+    };
+
+    let req: JsonRpcRequest = match serde_json::from_str(text) {
+        Ok(req) => req,
+        Err(err) => return render(Err(anyhow!("invalid json rpc request: {err}"))),
+    };
+
+    match req.method.as_str() {
+        json_rpc_methods::SUBSCRIBE => render((|| {
+            state.handlers.authorize(token, &req.method)?;
+            let p: SubscribeParams = serde_json::from_value(req.params)?;
+            let subscription = state.handlers.subscriptions().subscribe(p.topic, notify_tx.clone());
+            owned_subscriptions.insert(subscription);
+            Ok(serde_json::to_value(SubscribeResponse { subscription })?)
+        })()),
+        json_rpc_methods::UNSUBSCRIBE => render((|| {
+            state.handlers.authorize(token, &req.method)?;
+            let p: UnsubscribeParams = serde_json::from_value(req.params)?;
+            if !owned_subscriptions.remove(&p.subscription) {
+                return Err(anyhow!("no such subscription, or it belongs to a different connection"));
+            }
+            state.handlers.subscriptions().unsubscribe(p.subscription);
+            Ok(Value::Bool(true))
+        })()),
+        _ => render(state.handlers.handle(token, req.method, req.params).await),
+    }
+}
+
+/// Starts serving `handlers` at `config.json_rpc_address`. `config.with_http`/`with_ws` gate
+/// each transport independently (see `LaunchDaemonArgs`'s `--with-http`/`--with-ws` flags) so an
+/// operator can run http-only (the default, since `with_ws` is off unless enabled),
+/// websocket-only (e.g. to force clients through subscriptions), or both.
+pub async fn serve(handlers: Arc<Handlers>, config: &ServerConfig) -> Result<()> {
+    if !config.with_http && !config.with_ws {
+        return Err(anyhow!(
+            "at least one of the http or websocket transports must be enabled"
+        ));
+    }
+
+    let state = Arc::new(ServerState { handlers });
+
+    let mut router = Router::new();
+    if config.with_http {
+        router = router.route("/json_rpc", post(handle_http));
+    }
+    if config.with_ws {
+        router = router.route("/json_rpc", axum::routing::get(handle_ws_upgrade));
+    }
+    let router = router.with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(config.json_rpc_address).await?;
+    axum::serve(listener, router).await?;
+    Ok(())
+}